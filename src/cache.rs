@@ -1,6 +1,10 @@
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
 use std::str::from_utf8;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
 
 use cosmwasm_vm::{features_from_csv, Cache, CacheOptions, Checksum, Size};
 
@@ -11,14 +15,30 @@ use crate::memory::{Buffer, ByteSliceView};
 use crate::querier::GoQuerier;
 use crate::storage::GoStorage;
 
+/// The cosmwasm_vm cache plus the wasmvm-level settings that ride alongside
+/// it but aren't part of upstream `CacheOptions`.
+struct CacheImpl {
+    inner: Cache<GoApi, GoStorage, GoQuerier>,
+    /// Directory `load_wasm_mmap` writes its mmap-able shadow copies into.
+    /// Lives inside the cache's own data directory, fully owned by this
+    /// crate — see [`do_load_wasm_mmap`] for why.
+    mmap_cache_dir: PathBuf,
+}
+
+/// The cache as exposed to Go, guarded by an `RwLock` so that read-only
+/// operations (`load_wasm`, `analyze_code`) can run concurrently from
+/// multiple goroutines while mutating operations (`save_wasm`, `pin`,
+/// `unpin`) take an exclusive lock. This mirrors the opt-in thread-safety
+/// model other embedders use for their module caches: readers don't block
+/// each other, only writers block everyone.
 #[repr(C)]
 pub struct cache_t {}
 
-pub fn to_cache(ptr: *mut cache_t) -> Option<&'static mut Cache<GoApi, GoStorage, GoQuerier>> {
+pub fn to_cache(ptr: *mut cache_t) -> Option<&'static RwLock<CacheImpl>> {
     if ptr.is_null() {
         None
     } else {
-        let c = unsafe { &mut *(ptr as *mut Cache<GoApi, GoStorage, GoQuerier>) };
+        let c = unsafe { &*(ptr as *mut RwLock<CacheImpl>) };
         Some(c)
     }
 }
@@ -57,7 +77,7 @@ fn do_init_cache(
     supported_features: ByteSliceView,
     cache_size: u32,
     instance_memory_limit: u32, // in MiB
-) -> Result<*mut Cache<GoApi, GoStorage, GoQuerier>, Error> {
+) -> Result<*mut RwLock<CacheImpl>, Error> {
     let dir = data_dir
         .read()
         .ok_or_else(|| Error::empty_arg(DATA_DIR_ARG))?;
@@ -78,6 +98,7 @@ fn do_init_cache(
             .try_into()
             .expect("Cannot convert u32 to usize. What kind of system is this?"),
     );
+    let mmap_cache_dir = PathBuf::from(&dir_str).join("wasmvm-mmap-cache");
     let options = CacheOptions {
         base_dir: dir_str.into(),
         supported_features: features,
@@ -85,27 +106,30 @@ fn do_init_cache(
         instance_memory_limit,
     };
     let cache = unsafe { Cache::new(options) }?;
-    let out = Box::new(cache);
+    let out = Box::new(RwLock::new(CacheImpl {
+        inner: cache,
+        mmap_cache_dir,
+    }));
     Ok(Box::into_raw(out))
 }
 
 #[no_mangle]
 pub extern "C" fn save_wasm(cache: *mut cache_t, wasm: Buffer, err: Option<&mut Buffer>) -> Buffer {
     let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_save_wasm(c, wasm)))
-            .unwrap_or_else(|_| Err(Error::panic())),
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let mut cache = c.write().unwrap_or_else(|e| e.into_inner());
+            do_save_wasm(&mut cache, wasm)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
     };
     let data = handle_c_error_binary(r, err);
     Buffer::from_vec(data)
 }
 
-fn do_save_wasm(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    wasm: Buffer,
-) -> Result<Checksum, Error> {
+fn do_save_wasm(cache: &mut CacheImpl, wasm: Buffer) -> Result<Checksum, Error> {
     let wasm = unsafe { wasm.read() }.ok_or_else(|| Error::empty_arg(WASM_ARG))?;
-    let checksum = cache.save_wasm(wasm)?;
+    let checksum = cache.inner.save_wasm(wasm)?;
     Ok(checksum)
 }
 
@@ -116,73 +140,325 @@ pub extern "C" fn load_wasm(
     err: Option<&mut Buffer>,
 ) -> Buffer {
     let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_load_wasm(c, checksum)))
-            .unwrap_or_else(|_| Err(Error::panic())),
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let cache = c.read().unwrap_or_else(|e| e.into_inner());
+            do_load_wasm(&cache, checksum)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
     };
     let data = handle_c_error_binary(r, err);
     Buffer::from_vec(data)
 }
 
-fn do_load_wasm(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: Buffer,
-) -> Result<Vec<u8>, Error> {
+fn do_load_wasm(cache: &CacheImpl, checksum: Buffer) -> Result<Vec<u8>, Error> {
     let checksum: Checksum = unsafe { checksum.read() }
         .ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?
         .try_into()?;
-    let wasm = cache.load_wasm(&checksum)?;
+    let wasm = cache.inner.load_wasm(&checksum)?;
     Ok(wasm)
 }
 
+/// An opaque handle to a memory-mapped `.wasm` file. Go must pass this back
+/// into [`free_wasm_mapping`] exactly once to unmap the file; `data`/`len`
+/// on the paired [`WasmMapping`] become dangling once the mapping is
+/// released.
+#[repr(C)]
+pub struct wasm_mapping_t {}
+
+/// A `load_wasm` result backed by a memory mapping rather than an owned
+/// `Vec<u8>`. Deliberately a distinct type from `Buffer` (not just a reuse
+/// of its layout): it must be released through [`free_wasm_mapping`], never
+/// through the ordinary buffer-free path, and giving it its own type makes
+/// that a compile-time distinction on the Go side rather than a runtime
+/// footgun.
+#[repr(C)]
+pub struct WasmMapping {
+    /// Pointer to the start of the mapped wasm bytes. Only valid between
+    /// this call returning and `handle` being passed to
+    /// [`free_wasm_mapping`].
+    pub data: *const u8,
+    pub len: usize,
+    pub handle: *mut wasm_mapping_t,
+}
+
+/// Loads a wasm blob the same way `load_wasm` does, but hands back a memory
+/// mapping instead of copying the module into a freshly allocated
+/// `Vec<u8>`. This avoids the allocate-then-memcpy cost `load_wasm` pays on
+/// every call after the first, at the price of the caller having to release
+/// the mapping explicitly via [`free_wasm_mapping`] once it is done reading
+/// it.
+///
+/// `cosmwasm_vm::Cache` does not expose the on-disk path it stores a
+/// module's bytes under, so this maintains its own shadow copy (under
+/// `mmap_cache_dir`, written once per checksum the first time it is
+/// requested) purely so there is a file this crate can safely mmap and
+/// knows nobody else will rewrite in place.
+///
+/// Trade-off: the first call for a given checksum is strictly more
+/// expensive than `load_wasm`, not less — it pays for `load_wasm`'s read out
+/// of upstream's cache *and* a copy into the shadow file *and* the mapping
+/// itself. Only repeat calls for the same checksum come out ahead, by
+/// reusing the shadow file instead of re-reading and re-copying. Shadow
+/// files are not evicted individually; they are all removed in bulk when
+/// the owning cache is released via `release_cache`. Callers that only ever
+/// load a checksum once should keep using `load_wasm` instead.
+#[no_mangle]
+pub extern "C" fn load_wasm_mmap(
+    cache: *mut cache_t,
+    checksum: Buffer,
+    err: Option<&mut Buffer>,
+) -> WasmMapping {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let cache = c.read().unwrap_or_else(|e| e.into_inner());
+            do_load_wasm_mmap(&cache, checksum)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    match r {
+        Ok(mapping) => {
+            clear_error();
+            mapping
+        }
+        Err(error) => {
+            set_error(error, err);
+            WasmMapping {
+                data: std::ptr::null(),
+                len: 0,
+                handle: std::ptr::null_mut(),
+            }
+        }
+    }
+}
+
+/// Monotonic suffix for shadow-file temp names; see [`do_load_wasm_mmap`].
+static SHADOW_TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn do_load_wasm_mmap(cache: &CacheImpl, checksum: Buffer) -> Result<WasmMapping, Error> {
+    let checksum_bytes =
+        unsafe { checksum.read() }.ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?;
+    let checksum: Checksum = checksum_bytes.try_into()?;
+
+    let file_name = hex_encode(checksum_bytes);
+    let path = cache.mmap_cache_dir.join(&file_name);
+    if !path.exists() {
+        std::fs::create_dir_all(&cache.mmap_cache_dir)?;
+        let wasm = cache.inner.load_wasm(&checksum)?;
+        // `do_load_wasm_mmap` only ever takes the cache's shared read lock,
+        // so two threads can race to create the shadow file for the same
+        // checksum at once. Write each writer's copy to its own temp file
+        // and atomically rename it into place, rather than writing `path`
+        // directly: a concurrent reader then only ever observes either "not
+        // there yet" (and falls back to loading it itself) or the complete
+        // final file, never a torn or zero-padded one.
+        let unique = SHADOW_TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = cache
+            .mmap_cache_dir
+            .join(format!("{file_name}.tmp.{}.{unique}", std::process::id()));
+        std::fs::write(&tmp_path, &wasm)?;
+        std::fs::rename(&tmp_path, &path)?;
+    }
+
+    let file = std::fs::File::open(&path)?;
+    // Safety: `path` lives inside `mmap_cache_dir`, a directory this crate
+    // owns exclusively, and is only ever published via the atomic rename
+    // above, so the mapping stays valid for as long as the handle is held.
+    let mmap = raw_mmap::ReadOnlyMmap::map(&file)?;
+    let data = mmap.as_ptr();
+    let len = mmap.len();
+    let handle = Box::into_raw(Box::new(mmap)) as *mut wasm_mapping_t;
+    Ok(WasmMapping { data, len, handle })
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(s, "{:02x}", byte);
+    }
+    s
+}
+
+/// A minimal read-only file mapping built directly on the POSIX `mmap`/
+/// `munmap` syscalls, rather than on a crate dependency. `do_load_wasm_mmap`
+/// is the only caller; pulling in a whole mmap crate for one read-only
+/// mapping isn't worth a new dependency edge.
+mod raw_mmap {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+
+    const PROT_READ: i32 = 1;
+    const MAP_PRIVATE: i32 = 2;
+
+    /// A read-only, copy-on-write mapping of a whole file.
+    pub struct ReadOnlyMmap {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    impl ReadOnlyMmap {
+        pub fn map(file: &File) -> io::Result<Self> {
+            let len = file.metadata()?.len() as usize;
+            // `mmap` rejects a zero-length mapping; map one page for an
+            // empty file so `ptr` is always a valid, non-null mapping even
+            // though `len` (and therefore the reported slice) stays 0.
+            let map_len = len.max(1);
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    map_len,
+                    PROT_READ,
+                    MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            // mmap(2) returns MAP_FAILED, i.e. -1 cast to a pointer, on error.
+            if ptr as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(ReadOnlyMmap { ptr, len })
+        }
+
+        pub fn as_ptr(&self) -> *const u8 {
+            self.ptr as *const u8
+        }
+
+        pub fn len(&self) -> usize {
+            self.len
+        }
+    }
+
+    impl Drop for ReadOnlyMmap {
+        fn drop(&mut self) {
+            let map_len = self.len.max(1);
+            unsafe { munmap(self.ptr, map_len) };
+        }
+    }
+
+    // Safety: the mapping is read-only and never mutated through `ptr`, so
+    // sharing `&ReadOnlyMmap`/sending it across threads is sound.
+    unsafe impl Send for ReadOnlyMmap {}
+    unsafe impl Sync for ReadOnlyMmap {}
+}
+
+/// Releases a mapping previously returned by [`load_wasm_mmap`].
+///
+/// # Safety
+///
+/// This must be called exactly once for any `*wasm_mapping_t` returned by
+/// `load_wasm_mmap`, and `data`/`len` on the paired `WasmMapping` must not
+/// be read again afterwards.
+#[no_mangle]
+pub extern "C" fn free_wasm_mapping(handle: *mut wasm_mapping_t) {
+    if !handle.is_null() {
+        let _ = unsafe { Box::from_raw(handle as *mut raw_mmap::ReadOnlyMmap) };
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn pin(cache: *mut cache_t, checksum: Buffer, err: Option<&mut Buffer>) {
     let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_pin(c, checksum)))
-            .unwrap_or_else(|_| Err(Error::panic())),
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let mut cache = c.write().unwrap_or_else(|e| e.into_inner());
+            do_pin(&mut cache, checksum)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
     };
     handle_c_error_default(r, err);
 }
 
-fn do_pin(cache: &mut Cache<GoApi, GoStorage, GoQuerier>, checksum: Buffer) -> Result<(), Error> {
+fn do_pin(cache: &mut CacheImpl, checksum: Buffer) -> Result<(), Error> {
     let checksum: Checksum = unsafe { checksum.read() }
         .ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?
         .try_into()?;
-    cache.pin(&checksum)?;
+    cache.inner.pin(&checksum)?;
     Ok(())
 }
 
 #[no_mangle]
 pub extern "C" fn unpin(cache: *mut cache_t, checksum: Buffer, err: Option<&mut Buffer>) {
     let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_unpin(c, checksum)))
-            .unwrap_or_else(|_| Err(Error::panic())),
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let mut cache = c.write().unwrap_or_else(|e| e.into_inner());
+            do_unpin(&mut cache, checksum)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
     };
     handle_c_error_default(r, err);
 }
 
-fn do_unpin(cache: &mut Cache<GoApi, GoStorage, GoQuerier>, checksum: Buffer) -> Result<(), Error> {
+fn do_unpin(cache: &mut CacheImpl, checksum: Buffer) -> Result<(), Error> {
     let checksum: Checksum = unsafe { checksum.read() }
         .ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?
         .try_into()?;
-    cache.unpin(&checksum)?;
+    cache.inner.unpin(&checksum)?;
     Ok(())
 }
 
+/// The known CosmWasm entry points we look for among the module's export
+/// names (the export kind is not checked). Anything else the module exports
+/// is not an entry point as far as the host is concerned.
+const KNOWN_ENTRY_POINTS: &[&str] = &[
+    "instantiate",
+    "execute",
+    "query",
+    "migrate",
+    "sudo",
+    "reply",
+    "ibc_channel_open",
+    "ibc_channel_connect",
+    "ibc_channel_close",
+    "ibc_packet_receive",
+    "ibc_packet_ack",
+    "ibc_packet_timeout",
+    "ibc_source_callback",
+    "ibc_destination_callback",
+];
+
+/// Prefix CosmWasm contracts use on a marker export to declare a capability
+/// they require, e.g. an export named `requires_iterator` declares the
+/// `iterator` capability. This mirrors the convention contracts already use
+/// for `requires_staking`/`requires_stargate` and friends: a zero-sized
+/// function export rather than a dedicated custom section, since that's what
+/// real contracts emit.
+const REQUIRES_EXPORT_PREFIX: &str = "requires_";
+
 #[repr(C)]
-#[derive(Copy, Clone, Default, Debug, PartialEq)]
+#[derive(Default, Debug)]
 pub struct AnalysisReport {
     pub has_ibc_entry_points: bool,
-}
-
-impl From<cosmwasm_vm::AnalysisReport> for AnalysisReport {
-    fn from(report: cosmwasm_vm::AnalysisReport) -> Self {
-        AnalysisReport {
-            has_ibc_entry_points: report.has_ibc_entry_points,
-        }
-    }
+    /// Comma-separated list of the capabilities this module requires, read
+    /// from `requires_*` marker exports (empty if the module declares none).
+    ///
+    /// This `Buffer` owns a heap allocation. `AnalysisReport` intentionally
+    /// does not implement `Copy`/`Clone`: the caller must release it exactly
+    /// once, the same way it releases any other owned `Buffer` this crate
+    /// hands across the FFI boundary.
+    pub required_capabilities: Buffer,
+    /// Comma-separated list of the known CosmWasm entry points exported by
+    /// the module (e.g. `instantiate,execute,query`).
+    ///
+    /// Same ownership rule as `required_capabilities`.
+    pub entry_points: Buffer,
 }
 
 #[no_mangle]
@@ -192,8 +468,11 @@ pub extern "C" fn analyze_code(
     err: Option<&mut Buffer>,
 ) -> AnalysisReport {
     let r = match to_cache(cache) {
-        Some(c) => catch_unwind(AssertUnwindSafe(move || do_analyze_code(c, checksum)))
-            .unwrap_or_else(|_| Err(Error::panic())),
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            let cache = c.read().unwrap_or_else(|e| e.into_inner());
+            do_analyze_code(&cache, checksum)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
         None => Err(Error::empty_arg(CACHE_ARG)),
     };
     match r {
@@ -208,15 +487,128 @@ pub extern "C" fn analyze_code(
     }
 }
 
-fn do_analyze_code(
-    cache: &mut Cache<GoApi, GoStorage, GoQuerier>,
-    checksum: Buffer,
-) -> Result<AnalysisReport, Error> {
+fn do_analyze_code(cache: &CacheImpl, checksum: Buffer) -> Result<AnalysisReport, Error> {
     let checksum: Checksum = unsafe { checksum.read() }
         .ok_or_else(|| Error::empty_arg(CHECKSUM_ARG))?
         .try_into()?;
-    let report = cache.analyze(&checksum)?;
-    Ok(report.into())
+    let report = cache.inner.analyze(&checksum)?;
+    // `cosmwasm_vm::AnalysisReport` at this version only carries
+    // `has_ibc_entry_points`; the capabilities/entry-points lists are
+    // derived here, directly from the module bytes, rather than from
+    // upstream fields that don't exist yet.
+    let wasm = cache.inner.load_wasm(&checksum)?;
+    let (entry_points, required_capabilities) = inspect_module(&wasm);
+    let mut required_capabilities: Vec<String> = required_capabilities.into_iter().collect();
+    required_capabilities.sort();
+    Ok(AnalysisReport {
+        has_ibc_entry_points: report.has_ibc_entry_points,
+        required_capabilities: Buffer::from_vec(required_capabilities.join(",").into_bytes()),
+        entry_points: Buffer::from_vec(entry_points.join(",").into_bytes()),
+    })
+}
+
+/// Walks a wasm module's export section without pulling in a full wasm
+/// parser, extracting just what `AnalysisReport` needs: which of the
+/// [`KNOWN_ENTRY_POINTS`] the module exports, and which capabilities it
+/// declares via [`REQUIRES_EXPORT_PREFIX`] marker exports.
+///
+/// Malformed modules are tolerated by returning whatever was found before
+/// parsing gave up — `cache.inner.analyze` above is what rejects genuinely
+/// invalid wasm, so this only has to be robust, not strict.
+fn inspect_module(wasm: &[u8]) -> (Vec<String>, HashSet<String>) {
+    let mut entry_points = Vec::new();
+    let mut required_capabilities = HashSet::new();
+
+    // Skip the 4-byte magic number and 4-byte version.
+    let mut body = match wasm.get(8..) {
+        Some(body) => body,
+        None => return (entry_points, required_capabilities),
+    };
+
+    while let Some(&id) = body.first() {
+        let (len, rest) = match read_leb_u32(&body[1..]) {
+            Some(v) => v,
+            None => break,
+        };
+        let len = len as usize;
+        if rest.len() < len {
+            break;
+        }
+        let (section, remainder) = rest.split_at(len);
+        if id == 7 {
+            // export section
+            for name in parse_export_names(section) {
+                if let Some(capability) = name.strip_prefix(REQUIRES_EXPORT_PREFIX) {
+                    required_capabilities.insert(capability.to_string());
+                } else if KNOWN_ENTRY_POINTS.contains(&name.as_str()) {
+                    entry_points.push(name);
+                }
+            }
+        }
+        body = remainder;
+    }
+
+    (entry_points, required_capabilities)
+}
+
+/// Parses a wasm export section, returning the name of every export.
+/// Callers classify each name themselves (entry point vs. capability
+/// marker vs. something else entirely).
+fn parse_export_names(section: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let (count, mut rest) = match read_leb_u32(section) {
+        Some(v) => v,
+        None => return names,
+    };
+    for _ in 0..count {
+        let (name, after_name) = match read_name(rest) {
+            Some(v) => v,
+            None => break,
+        };
+        // Each export is `name, kind:u8, index:varu32`; we only need the
+        // name, so skip the kind byte and the index we don't use.
+        let after_kind = match after_name.get(1..) {
+            Some(v) => v,
+            None => break,
+        };
+        let (_index, after_index) = match read_leb_u32(after_kind) {
+            Some(v) => v,
+            None => break,
+        };
+        names.push(name.to_string());
+        rest = after_index;
+    }
+    names
+}
+
+/// Reads a wasm length-prefixed UTF-8 name, returning it along with the
+/// remainder of `bytes`.
+fn read_name(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let (len, rest) = read_leb_u32(bytes)?;
+    let len = len as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (name, remainder) = rest.split_at(len);
+    from_utf8(name).ok().map(|name| (name, remainder))
+}
+
+/// Reads an unsigned LEB128 `u32`, returning it along with the remainder of
+/// `bytes`.
+fn read_leb_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let mut result: u32 = 0;
+    let mut shift = 0u32;
+    for (i, &byte) in bytes.iter().enumerate() {
+        result |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some((result, &bytes[i + 1..]));
+        }
+        shift += 7;
+        if shift >= 32 {
+            return None;
+        }
+    }
+    None
 }
 
 /// frees a cache reference
@@ -228,8 +620,13 @@ fn do_analyze_code(
 #[no_mangle]
 pub extern "C" fn release_cache(cache: *mut cache_t) {
     if !cache.is_null() {
-        // this will free cache when it goes out of scope
-        let _ = unsafe { Box::from_raw(cache as *mut Cache<GoApi, GoStorage, GoQuerier>) };
+        let lock = unsafe { Box::from_raw(cache as *mut RwLock<CacheImpl>) };
+        let cache = lock.into_inner().unwrap_or_else(|e| e.into_inner());
+        // `load_wasm_mmap`'s shadow files have no per-file eviction (see its
+        // doc comment); reclaim all of them in bulk now that no other thread
+        // holds the cache's lock. Mappings not yet passed to
+        // `free_wasm_mapping` stay readable: unlinking a file does not unmap it.
+        let _ = std::fs::remove_dir_all(&cache.mmap_cache_dir);
     }
 }
 
@@ -318,6 +715,32 @@ mod tests {
         release_cache(cache_ptr);
     }
 
+    #[test]
+    fn load_wasm_mmap_works() {
+        let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
+        let mut err = Buffer::default();
+        let features: &[u8] = b"staking";
+        let cache_ptr = init_cache(
+            ByteSliceView::new(Some(dir.as_bytes())),
+            ByteSliceView::new(Some(features)),
+            512,
+            32,
+            Some(&mut err),
+        );
+        assert_eq!(err.len, 0);
+
+        let checksum = save_wasm(cache_ptr, HACKATOM.into(), Some(&mut err));
+        assert_eq!(err.len, 0);
+
+        let mapping = load_wasm_mmap(cache_ptr, checksum, Some(&mut err));
+        assert_eq!(err.len, 0);
+        let mapped = unsafe { std::slice::from_raw_parts(mapping.data, mapping.len) };
+        assert_eq!(mapped, HACKATOM);
+        free_wasm_mapping(mapping.handle);
+
+        release_cache(cache_ptr);
+    }
+
     #[test]
     fn pin_works() {
         let dir: String = TempDir::new().unwrap().path().to_str().unwrap().to_owned();
@@ -395,19 +818,29 @@ mod tests {
         assert_eq!(err.len, 0);
 
         let hackatom_report = analyze_code(cache_ptr, checksum_hackatom, Some(&mut err));
-        assert_eq!(
-            hackatom_report,
-            AnalysisReport {
-                has_ibc_entry_points: false
-            }
-        );
+        assert!(!hackatom_report.has_ibc_entry_points);
+        let hackatom_entry_points =
+            String::from_utf8(unsafe { hackatom_report.entry_points.consume() }).unwrap();
+        assert!(hackatom_entry_points.contains("instantiate"));
+        assert!(hackatom_entry_points.contains("execute"));
+        assert!(!hackatom_entry_points.contains("ibc_channel_open"));
+        // hackatom declares no `requires_*` marker export, so we expect no
+        // capabilities.
+        let hackatom_capabilities =
+            String::from_utf8(unsafe { hackatom_report.required_capabilities.consume() }).unwrap();
+        assert_eq!(hackatom_capabilities, "");
+
         let ibc_reflect_report = analyze_code(cache_ptr, checksum_ibc_reflect, Some(&mut err));
-        assert_eq!(
-            ibc_reflect_report,
-            AnalysisReport {
-                has_ibc_entry_points: true
-            }
-        );
+        assert!(ibc_reflect_report.has_ibc_entry_points);
+        let ibc_reflect_entry_points =
+            String::from_utf8(unsafe { ibc_reflect_report.entry_points.consume() }).unwrap();
+        assert!(ibc_reflect_entry_points.contains("ibc_channel_open"));
+        // ibc_reflect is built with the `stargate` feature and exports
+        // `requires_stargate`, which is why this cache supports `stargate`.
+        let ibc_reflect_capabilities =
+            String::from_utf8(unsafe { ibc_reflect_report.required_capabilities.consume() })
+                .unwrap();
+        assert_eq!(ibc_reflect_capabilities, "stargate");
 
         release_cache(cache_ptr);
     }